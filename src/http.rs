@@ -1,18 +1,27 @@
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
-use std::net::TcpStream;
+use std::io::{BufReader, Read, Write};
+
+use brotli::CompressorWriter;
+use flate2::write::{DeflateEncoder, GzEncoder};
+use flate2::Compression;
 
 use crate::http::parser::Parser;
 
+pub mod date;
 mod parser;
 
 pub enum HttpStatus {
+    Continue,
     OK,
     Created,
+    PartialContent,
+    NotModified,
     BadRequest,
     Unauthorized,
     Forbidden,
     NotFound,
+    RangeNotSatisfiable,
     MethodNotAllowed,
     InternalError,
 }
@@ -49,6 +58,7 @@ pub enum HttpError {
     NotFound(String),
     MethodNotAllowed(Vec<HttpMethod>),
     BadRequest(ParseError),
+    RangeNotSatisfiable(u64),
     InternalError,
     Unauthorized,
     Forbidden,
@@ -91,14 +101,14 @@ pub struct HttpResponse {
     version: HttpVersion,
     status: HttpStatus,
     headers: HttpHeaderCollection,
-    body: String,
+    body: Vec<u8>,
 }
 
 pub struct HttpResponseBuilder {
     version: Option<HttpVersion>,
     status: Option<HttpStatus>,
     headers: HttpHeaderCollection,
-    body: Option<String>,
+    body: Option<Vec<u8>>,
 }
 
 #[derive(Debug)]
@@ -109,17 +119,21 @@ pub enum ParseError {
     MissingVersion,
     MissingPath,
     MalformedRequest,
+    ConnectionClosed,
     Unreachable,
 }
 
 impl HttpRequest {
-    pub fn from_stream(stream: &TcpStream) -> Result<Self, ParseError> {
+    pub fn from_stream<R: Read, W: Write>(
+        reader: &mut BufReader<R>,
+        writer: &mut W,
+    ) -> Result<Self, ParseError> {
         let parser = Parser::new();
 
-        Ok(parser
-            .parse(stream)?
+        parser
+            .parse(reader, writer)?
             .get_request()
-            .map_err(|_| ParseError::Unreachable)?)
+            .map_err(|_| ParseError::Unreachable)
     }
 }
 
@@ -156,7 +170,7 @@ impl HttpResponse {
         Self {
             version,
             status,
-            body: String::new(),
+            body: Vec::new(),
             headers: HttpHeaderCollection::new(),
         }
     }
@@ -171,9 +185,22 @@ impl HttpResponse {
             version,
             status,
             headers,
-            body: String::new(),
+            body: Vec::new(),
         }
     }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        write!(writer, "{} {}\r\n{}\r\n", self.version, self.status, self.headers)?;
+        writer.write_all(&self.body)
+    }
+
+    pub fn add_header(&mut self, name: String, value: String) {
+        self.headers.add_header(name, value);
+    }
+
+    pub fn status_code(&self) -> u16 {
+        self.status.code()
+    }
 }
 
 impl HttpResponseBuilder {
@@ -198,20 +225,22 @@ impl HttpResponseBuilder {
 
     pub fn with_body(
         mut self,
-        body: String,
+        body: Vec<u8>,
         mime_type: MimeType,
         encoding: Option<HttpEncoding>,
     ) -> Self {
+        let body = match encoding {
+            Some(encoding) if encoding != HttpEncoding::Identity && encoding != HttpEncoding::Unsupported => {
+                self.headers
+                    .add_header("Content-Encoding".to_string(), encoding.to_string());
+                encoding.compress(body)
+            }
+            _ => body,
+        };
         self.headers
             .add_header("Content-Type".to_string(), mime_type.to_string());
         self.headers
             .add_header("Content-Length".to_string(), body.len().to_string());
-        if let Some(encoding) = encoding {
-            if encoding != HttpEncoding::Unsupported {
-                self.headers
-                    .add_header("Content-Encoding".to_string(), encoding.to_string());
-            }
-        }
         self.body = Some(body);
         self
     }
@@ -235,7 +264,7 @@ impl HttpResponseBuilder {
     pub fn to_response(self) -> HttpResponse {
         let version = self.version.unwrap_or(HttpVersion::V11);
         let status = self.status.unwrap_or(HttpStatus::OK);
-        let body = self.body.unwrap_or(String::new());
+        let body = self.body.unwrap_or_default();
 
         HttpResponse {
             version,
@@ -264,6 +293,10 @@ impl HttpError {
                 )
             }
             HttpError::BadRequest(_) => HttpResponse::new(HttpVersion::V11, HttpStatus::BadRequest),
+            HttpError::RangeNotSatisfiable(total) => HttpResponseBuilder::new()
+                .with_status(HttpStatus::RangeNotSatisfiable)
+                .add_header("Content-Range".to_string(), format!("bytes */{total}"))
+                .to_response(),
             HttpError::InternalError => HttpResponseBuilder::new()
                 .with_status(HttpStatus::InternalError)
                 .to_response(),
@@ -337,19 +370,32 @@ impl HttpHeaderCollection {
     */
 }
 
-impl Display for HttpStatus {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let (code, description) = match self {
+impl HttpStatus {
+    fn code_and_description(&self) -> (u16, &'static str) {
+        match self {
+            HttpStatus::Continue => (100, "Continue"),
             HttpStatus::OK => (200, "OK"),
+            HttpStatus::PartialContent => (206, "Partial Content"),
+            HttpStatus::NotModified => (304, "Not Modified"),
             HttpStatus::MethodNotAllowed => (405, "Method Not Allowed"),
             HttpStatus::NotFound => (404, "Not Found"),
+            HttpStatus::RangeNotSatisfiable => (416, "Range Not Satisfiable"),
             HttpStatus::BadRequest => (400, "Bad Request"),
             HttpStatus::InternalError => (500, "Internal Server Error"),
             HttpStatus::Created => (201, "Created"),
             HttpStatus::Unauthorized => (401, "Unauthorized"),
             HttpStatus::Forbidden => (403, "Forbidden"),
-        };
+        }
+    }
 
+    pub fn code(&self) -> u16 {
+        self.code_and_description().0
+    }
+}
+
+impl Display for HttpStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (code, description) = self.code_and_description();
         write!(f, "{code} {description}")
     }
 }
@@ -372,16 +418,6 @@ impl Display for HttpMethod {
     }
 }
 
-impl Display for HttpResponse {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{} {}\r\n{}\r\n{}",
-            self.version, self.status, self.headers, self.body
-        )
-    }
-}
-
 impl Display for HttpRequest {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let body_string = match &self.body {
@@ -457,6 +493,52 @@ impl From<&String> for HttpEncoding {
     }
 }
 
+impl HttpEncoding {
+    const SUPPORTED: [HttpEncoding; 3] = [
+        HttpEncoding::Gzip,
+        HttpEncoding::Deflate,
+        HttpEncoding::Brotli,
+    ];
+
+    /// Picks the highest-quality encoding the server can actually produce out of a
+    /// parsed `Accept-Encoding` list, falling back to `Identity` when none match.
+    pub fn negotiate(accepted: &[(HttpEncoding, f32)]) -> HttpEncoding {
+        let mut best: Option<(HttpEncoding, f32)> = None;
+        for &(encoding, quality) in accepted {
+            if quality <= 0.0 || !Self::SUPPORTED.contains(&encoding) {
+                continue;
+            }
+            if best.is_none_or(|(_, best_quality)| quality > best_quality) {
+                best = Some((encoding, quality));
+            }
+        }
+        best.map(|(encoding, _)| encoding).unwrap_or(HttpEncoding::Identity)
+    }
+
+    pub fn compress(&self, body: Vec<u8>) -> Vec<u8> {
+        match self {
+            HttpEncoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&body).expect("in-memory gzip write cannot fail");
+                encoder.finish().expect("in-memory gzip finish cannot fail")
+            }
+            HttpEncoding::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&body).expect("in-memory deflate write cannot fail");
+                encoder.finish().expect("in-memory deflate finish cannot fail")
+            }
+            HttpEncoding::Brotli => {
+                let mut output = Vec::new();
+                let mut writer = CompressorWriter::new(&mut output, 4096, 11, 22);
+                writer.write_all(&body).expect("in-memory brotli write cannot fail");
+                drop(writer);
+                output
+            }
+            _ => body,
+        }
+    }
+}
+
 impl Display for HttpEncoding {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let string_representation = match self {
@@ -472,3 +554,37 @@ impl Display for HttpEncoding {
         write!(f, "{string_representation}")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiates_the_highest_quality_supported_encoding() {
+        let accepted = [(HttpEncoding::Gzip, 0.5), (HttpEncoding::Brotli, 0.8)];
+        assert_eq!(HttpEncoding::negotiate(&accepted), HttpEncoding::Brotli);
+    }
+
+    #[test]
+    fn falls_back_to_identity_when_nothing_is_supported() {
+        let accepted = [(HttpEncoding::Exi, 1.0), (HttpEncoding::Compress, 1.0)];
+        assert_eq!(HttpEncoding::negotiate(&accepted), HttpEncoding::Identity);
+    }
+
+    #[test]
+    fn ignores_q_zero_encodings() {
+        let accepted = [(HttpEncoding::Gzip, 0.0), (HttpEncoding::Deflate, 0.5)];
+        assert_eq!(HttpEncoding::negotiate(&accepted), HttpEncoding::Deflate);
+    }
+
+    #[test]
+    fn first_listed_wins_a_quality_tie() {
+        let accepted = [(HttpEncoding::Gzip, 1.0), (HttpEncoding::Brotli, 1.0)];
+        assert_eq!(HttpEncoding::negotiate(&accepted), HttpEncoding::Gzip);
+    }
+
+    #[test]
+    fn empty_accept_list_falls_back_to_identity() {
+        assert_eq!(HttpEncoding::negotiate(&[]), HttpEncoding::Identity);
+    }
+}
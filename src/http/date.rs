@@ -0,0 +1,102 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Formats a timestamp as an RFC 1123 date, e.g. `Thu, 01 Jan 1970 00:00:00 GMT`.
+pub fn format_http_date(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let days = (secs / 86_400) as i64;
+    let time_of_day = secs % 86_400;
+    let (hour, minute, second) = (time_of_day / 3600, (time_of_day / 60) % 60, time_of_day % 60);
+
+    let (year, month, day) = civil_from_days(days);
+    let weekday = WEEKDAYS[(days + 4).rem_euclid(7) as usize];
+    let month_name = MONTHS[(month - 1) as usize];
+
+    format!("{weekday}, {day:02} {month_name} {year} {hour:02}:{minute:02}:{second:02} GMT")
+}
+
+/// Parses an RFC 1123 date produced by [`format_http_date`]. Returns `None` when the day,
+/// month name, year or time can't be read, which callers should treat as an unusable
+/// conditional header. The weekday and zone name are not checked.
+pub fn parse_http_date(input: &str) -> Option<SystemTime> {
+    let mut parts = input.split_ascii_whitespace();
+    parts.next()?; // weekday, not needed to reconstruct the timestamp
+    let day: u32 = parts.next()?.parse().ok()?;
+    let month_name = parts.next()?;
+    let month = MONTHS.iter().position(|m| *m == month_name)? as u32 + 1;
+    let year: i64 = parts.next()?.parse().ok()?;
+    let mut time_parts = parts.next()?.split(':');
+    let hour: u64 = time_parts.next()?.parse().ok()?;
+    let minute: u64 = time_parts.next()?.parse().ok()?;
+    let second: u64 = time_parts.next()?.parse().ok()?;
+
+    let days = days_from_civil(year, month, day);
+    let secs = days as u64 * 86_400 + hour * 3600 + minute * 60 + second;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Howard Hinnant's days-from-civil algorithm: maps a proleptic Gregorian date to a day
+/// count relative to the Unix epoch.
+fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = (y - era * 400) as u64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) as u64 + 2) / 5 + d as u64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe as i64 - 719_468
+}
+
+/// The inverse of [`days_from_civil`]: maps a day count relative to the Unix epoch back to
+/// a `(year, month, day)` proleptic Gregorian date.
+fn civil_from_days(z: i64) -> (i64, u32, u32) {
+    let z = z + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = (z - era * 146_097) as u64;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let y = yoe as i64 + era * 400;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = if m <= 2 { y + 1 } else { y };
+    (year, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_epoch() {
+        let formatted = format_http_date(UNIX_EPOCH);
+        assert_eq!(formatted, "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(parse_http_date(&formatted), Some(UNIX_EPOCH));
+    }
+
+    #[test]
+    fn round_trips_an_arbitrary_timestamp() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(parse_http_date(&format_http_date(time)), Some(time));
+    }
+
+    #[test]
+    fn parses_each_month_correctly() {
+        for month in MONTHS {
+            let input = format!("Mon, 15 {month} 2024 00:00:00 GMT");
+            let parsed = parse_http_date(&input).expect("valid date should parse");
+            let formatted = format_http_date(parsed);
+            assert!(formatted.ends_with(&format!("15 {month} 2024 00:00:00 GMT")));
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert_eq!(parse_http_date(""), None);
+        assert_eq!(parse_http_date("not a date"), None);
+        assert_eq!(parse_http_date("Thu, 01 Xyz 1970 00:00:00 GMT"), None);
+    }
+}
@@ -1,11 +1,19 @@
-use std::collections::BTreeSet;
-use std::io::{BufRead, BufReader, Read};
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
 
 use crate::http::{
-    HttpEncoding, HttpHeaderCollection, HttpMethod, HttpRequest, HttpVersion,
-    ParseError,
+    HttpEncoding, HttpHeaderCollection, HttpMethod, HttpRequest, HttpResponse, HttpStatus,
+    HttpVersion, ParseError,
 };
 
+/// A read that timed out (idle connection) looks identical to a dropped one from here, so
+/// treat it the same way rather than reporting it as a malformed request.
+fn io_timeout_to_closed(error: io::Error) -> ParseError {
+    match error.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => ParseError::ConnectionClosed,
+        _ => ParseError::MalformedRequest,
+    }
+}
+
 #[derive(Eq, PartialEq)]
 enum ParserState {
     Start,
@@ -22,7 +30,8 @@ pub struct Parser {
     headers: HttpHeaderCollection,
     body: Option<Vec<u8>>,
     content_length: Option<usize>,
-    content_encoding: BTreeSet<HttpEncoding>,
+    accepted_encodings: Vec<(HttpEncoding, f32)>,
+    expects_continue: bool,
 }
 
 pub struct Unparsed;
@@ -37,7 +46,8 @@ impl Parser {
             headers: HttpHeaderCollection::new(),
             body: None,
             content_length: None,
-            content_encoding: BTreeSet::new(),
+            accepted_encodings: Vec::new(),
+            expects_continue: false,
         }
     }
 
@@ -51,25 +61,26 @@ impl Parser {
             path: self.path.unwrap(),
             version: self.version.unwrap(),
             headers: self.headers,
-            encoding: self.content_encoding.iter().next().copied(),
+            encoding: Some(HttpEncoding::negotiate(&self.accepted_encodings)),
             body: self.body,
         })
     }
 
-    pub fn parse<R>(mut self, stream: R) -> Result<Self, ParseError>
+    pub fn parse<R, W>(mut self, reader: &mut BufReader<R>, writer: &mut W) -> Result<Self, ParseError>
     where
         R: Read,
+        W: Write,
     {
-        let mut reader = BufReader::new(stream);
         let mut line = String::new();
 
         while self.state != ParserState::Done && self.state != ParserState::Body {
             line.clear();
-            let bytes_read = reader
-                .read_line(&mut line)
-                .map_err(|_| ParseError::MalformedRequest)?;
+            let bytes_read = reader.read_line(&mut line).map_err(io_timeout_to_closed)?;
             if bytes_read == 0 {
-                break;
+                if self.state == ParserState::Start {
+                    return Err(ParseError::ConnectionClosed);
+                }
+                return Err(ParseError::MalformedRequest);
             }
             match self.state {
                 ParserState::Start => self.parse_start_line(&line)?,
@@ -80,7 +91,14 @@ impl Parser {
         }
 
         if self.state == ParserState::Body {
-            self.parse_body(&mut reader)?;
+            // Curl and friends pause after the headers until they see this, so send it before
+            // reading the body. HTTP/1.0 clients can't understand it and get no interim response.
+            if self.expects_continue && !matches!(self.version, Some(HttpVersion::V10)) {
+                HttpResponse::new(HttpVersion::V11, HttpStatus::Continue)
+                    .write_to(writer)
+                    .map_err(|_| ParseError::MalformedRequest)?;
+            }
+            self.parse_body(reader)?;
         }
         Ok(self)
     }
@@ -125,9 +143,21 @@ impl Parser {
         if key.eq_ignore_ascii_case("Content-Length") {
             self.content_length = Some(value.parse().map_err(|_| ParseError::MalformedRequest)?);
         }
+        if key.eq_ignore_ascii_case("Expect") && value.eq_ignore_ascii_case("100-continue") {
+            self.expects_continue = true;
+        }
         if key.eq_ignore_ascii_case("Accept-Encoding") {
-            for encoding_string in value.split(",") {
-                self.content_encoding.insert(HttpEncoding::from(encoding_string.trim()));
+            for entry in value.split(',') {
+                let mut params = entry.trim().split(';');
+                let Some(token) = params.next() else {
+                    continue;
+                };
+                let encoding = HttpEncoding::from(token.trim());
+                let quality = params
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .and_then(|q| q.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                self.accepted_encodings.push((encoding, quality));
             }
         }
 
@@ -141,9 +171,7 @@ impl Parser {
     {
         if let Some(length) = self.content_length {
             let mut buffer = vec![0; length];
-            reader
-                .read_exact(&mut buffer)
-                .map_err(|_| ParseError::MalformedRequest)?;
+            reader.read_exact(&mut buffer).map_err(io_timeout_to_closed)?;
             self.body = Some(buffer);
         }
 
@@ -1,13 +1,22 @@
 use std::env;
-use std::fs::File;
-use std::io::{ErrorKind, Read, Write};
+use std::fs::{File, Metadata};
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
 use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use crate::http::date::{format_http_date, parse_http_date};
 use crate::http::{
-    HttpError, HttpMethod, HttpRequest, HttpResponse, HttpResponseBuilder, HttpStatus, MimeType,
+    HttpError, HttpMethod, HttpRequest, HttpResponse, HttpResponseBuilder, HttpStatus, HttpVersion,
+    MimeType, ParseError,
 };
+use crate::middleware::{Cors, MiddlewareChain, RequestLogger};
 
 mod http;
+mod middleware;
+
+const IDLE_READ_TIMEOUT: Duration = Duration::from_secs(30);
 
 fn main() {
     // Get the directory flag if specified
@@ -23,23 +32,23 @@ fn main() {
         }
     }
 
-    let directory = directory.unwrap_or(".".to_string());
+    let directory = Arc::new(directory.unwrap_or(".".to_string()));
     println!("Directory: {directory}\n");
 
+    let middlewares = Arc::new(MiddlewareChain::new(vec![
+        Box::new(RequestLogger),
+        Box::new(Cors::new(vec!["http://localhost:3000".to_string()])),
+    ]));
+
     let listener = TcpListener::bind("127.0.0.1:4221").unwrap();
 
     for stream in listener.incoming() {
         match stream {
-            Ok(mut stream) => {
+            Ok(stream) => {
                 println!("accepted new connection");
-                let response = handle_request(&stream, &directory);
-
-                let response = match response {
-                    Ok(good_response) => format!("{good_response}"),
-                    Err(bad_response) => format!("{}", bad_response.to_response()),
-                };
-
-                stream.write_all(response.as_bytes()).unwrap();
+                let directory = Arc::clone(&directory);
+                let middlewares = Arc::clone(&middlewares);
+                thread::spawn(move || handle_connection(stream, &directory, &middlewares));
             }
             Err(e) => {
                 println!("error: {}", e);
@@ -48,30 +57,82 @@ fn main() {
     }
 }
 
-fn handle_request(stream: &TcpStream, root: &String) -> Result<HttpResponse, HttpError> {
-    let request = HttpRequest::from_stream(&stream)?;
+/// Services requests on `stream` until the client asks to close the connection,
+/// falls silent past the idle timeout, or sends something the parser can't recover from.
+fn handle_connection(stream: TcpStream, root: &String, middlewares: &MiddlewareChain) {
+    if let Err(e) = stream.set_read_timeout(Some(IDLE_READ_TIMEOUT)) {
+        println!("error setting read timeout: {e}");
+    }
+
+    let mut reader = BufReader::new(&stream);
+    let mut writer = &stream;
+
+    loop {
+        let request = match HttpRequest::from_stream(&mut reader, &mut writer) {
+            Ok(request) => request,
+            Err(ParseError::ConnectionClosed) => break,
+            Err(e) => {
+                let _ = HttpError::from(e).to_response().write_to(&mut writer);
+                break;
+            }
+        };
+
+        let keep_alive = should_keep_alive(&request);
+        let mut response = middlewares
+            .run(&request, &|request| route_request(request, root))
+            .unwrap_or_else(|e| e.to_response());
+        response.add_header(
+            "Connection".to_string(),
+            (if keep_alive { "keep-alive" } else { "close" }).to_string(),
+        );
+
+        if let Err(e) = response.write_to(&mut writer) {
+            println!("error writing response: {e}");
+            break;
+        }
+
+        if !keep_alive {
+            break;
+        }
+    }
+}
+
+/// HTTP/1.1+ defaults to persistent connections; HTTP/1.0 only stays open when asked to.
+/// An explicit `Connection` header always wins over the version default.
+fn should_keep_alive(request: &HttpRequest) -> bool {
+    match request.headers.get_value(&"Connection".to_string()) {
+        Some(value) if value.eq_ignore_ascii_case("close") => false,
+        Some(value) if value.eq_ignore_ascii_case("keep-alive") => true,
+        _ => !matches!(request.version, HttpVersion::V10),
+    }
+}
 
+fn route_request(request: &HttpRequest, root: &String) -> Result<HttpResponse, HttpError> {
     match request.path.as_str() {
         "/" => {
-            assert_method(&request, vec![HttpMethod::GET])?;
+            assert_method(request, vec![HttpMethod::GET])?;
             Ok(HttpResponseBuilder::new().to_response())
         }
         "/user-agent" => {
-            assert_method(&request, vec![HttpMethod::GET])?;
+            assert_method(request, vec![HttpMethod::GET])?;
             println!("Reading User-Agent Header");
             let mut response_builder = HttpResponseBuilder::new();
             if let Some(user_agent) = request.headers.get_value(&"User-Agent".to_string()) {
-                response_builder = response_builder.with_body(user_agent, MimeType::PlainText);
+                response_builder = response_builder.with_body(
+                    user_agent.into_bytes(),
+                    MimeType::PlainText,
+                    request.encoding,
+                );
             };
             Ok(response_builder.to_response())
         }
         path if path.starts_with("/echo/") => {
-            assert_method(&request, vec![HttpMethod::GET])?;
+            assert_method(request, vec![HttpMethod::GET])?;
             let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
             let param = parts.get(1).unwrap_or(&"");
             println!("Echoing back the parameter {param}");
             let response = HttpResponseBuilder::new()
-                .with_body(param.to_string(), MimeType::PlainText)
+                .with_body(param.to_string().into_bytes(), MimeType::PlainText, request.encoding)
                 .to_response();
             Ok(response)
         }
@@ -81,14 +142,47 @@ fn handle_request(stream: &TcpStream, root: &String) -> Result<HttpResponse, Htt
             match request.method {
                 HttpMethod::GET => {
                     println!("Returning back the file {root}{param}");
+                    let metadata = file_metadata(root, &param.to_string())?;
+                    let (etag, modified) = file_validators(&metadata);
+                    let last_modified = format_http_date(modified);
+
+                    if request_not_modified(request, &etag, modified) {
+                        return Ok(HttpResponseBuilder::new()
+                            .with_status(HttpStatus::NotModified)
+                            .add_header("ETag".to_string(), etag)
+                            .add_header("Last-Modified".to_string(), last_modified)
+                            .to_response());
+                    }
+
+                    if let Some(range) = request.headers.get_value(&"Range".to_string()) {
+                        let total = metadata.len();
+                        let (start, end) = parse_range(&range, total)
+                            .filter(|&(start, end)| start <= end && start < total)
+                            .ok_or(HttpError::RangeNotSatisfiable(total))?;
+                        let body = get_file_range(root, &param.to_string(), start, end)?;
+                        return Ok(HttpResponseBuilder::new()
+                            .with_status(HttpStatus::PartialContent)
+                            .add_header("ETag".to_string(), etag)
+                            .add_header("Last-Modified".to_string(), last_modified)
+                            .add_header(
+                                "Content-Range".to_string(),
+                                format!("bytes {start}-{end}/{total}"),
+                            )
+                            .with_body(body, MimeType::OctetStream, request.encoding)
+                            .to_response());
+                    }
+
                     let body = get_file(root, &param.to_string())?;
                     Ok(HttpResponseBuilder::new()
-                        .with_body(body, MimeType::OctetStream)
+                        .add_header("ETag".to_string(), etag)
+                        .add_header("Last-Modified".to_string(), last_modified)
+                        .add_header("Accept-Ranges".to_string(), "bytes".to_string())
+                        .with_body(body, MimeType::OctetStream, request.encoding)
                         .to_response())
                 }
                 HttpMethod::POST => {
                     println!("Saving file {root}{param}");
-                    write_file(root, &param.to_string(), &String::from_utf8(request.body.unwrap()).map_err(|_| HttpError::InternalError)?)?;
+                    write_file(root, &param.to_string(), &String::from_utf8(request.body.clone().unwrap_or_default()).map_err(|_| HttpError::InternalError)?)?;
                     Ok(HttpResponseBuilder::new()
                         .with_status(HttpStatus::Created)
                         .to_response())
@@ -103,14 +197,49 @@ fn handle_request(stream: &TcpStream, root: &String) -> Result<HttpResponse, Htt
     }
 }
 
-fn get_file(directory: &String, filename: &String) -> Result<String, HttpError> {
+fn file_metadata(directory: &String, filename: &String) -> Result<Metadata, HttpError> {
+    let path = format!("{directory}{filename}");
+
+    std::fs::metadata(path).map_err(|e| {
+        if e.kind() == ErrorKind::NotFound {
+            HttpError::NotFound(filename.clone())
+        } else {
+            HttpError::InternalError
+        }
+    })
+}
+
+/// Derives the `ETag` and `Last-Modified` validators for a file from its metadata.
+fn file_validators(metadata: &Metadata) -> (String, SystemTime) {
+    let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+    let mtime_secs = modified.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let etag = format!("\"{}-{}\"", metadata.len(), mtime_secs);
+    (etag, modified)
+}
+
+/// `If-None-Match` takes precedence over `If-Modified-Since` when both are present.
+fn request_not_modified(request: &HttpRequest, etag: &str, modified: SystemTime) -> bool {
+    if let Some(if_none_match) = request.headers.get_value(&"If-None-Match".to_string()) {
+        return if_none_match.split(',').any(|candidate| candidate.trim() == etag);
+    }
+
+    if let Some(if_modified_since) = request.headers.get_value(&"If-Modified-Since".to_string()) {
+        if let Some(since) = parse_http_date(&if_modified_since) {
+            return modified <= since;
+        }
+    }
+
+    false
+}
+
+fn get_file(directory: &String, filename: &String) -> Result<Vec<u8>, HttpError> {
     let path = format!("{directory}{filename}");
 
     match File::open(path) {
         Ok(mut file) => {
-            let mut buffer = String::new();
-            match file.read_to_string(&mut buffer) {
-                Ok(_) => Ok(buffer.clone()),
+            let mut buffer = Vec::new();
+            match file.read_to_end(&mut buffer) {
+                Ok(_) => Ok(buffer),
                 Err(_) => Err(HttpError::InternalError),
             }
         }
@@ -124,6 +253,55 @@ fn get_file(directory: &String, filename: &String) -> Result<String, HttpError>
     }
 }
 
+/// Reads the inclusive byte range `start..=end` out of the file without loading the rest of it.
+fn get_file_range(
+    directory: &String,
+    filename: &String,
+    start: u64,
+    end: u64,
+) -> Result<Vec<u8>, HttpError> {
+    let path = format!("{directory}{filename}");
+
+    let mut file = File::open(path).map_err(|e| {
+        if e.kind() == ErrorKind::NotFound {
+            HttpError::NotFound(filename.clone())
+        } else {
+            HttpError::InternalError
+        }
+    })?;
+
+    file.seek(SeekFrom::Start(start))
+        .map_err(|_| HttpError::InternalError)?;
+
+    let mut buffer = vec![0u8; (end - start + 1) as usize];
+    file.read_exact(&mut buffer)
+        .map_err(|_| HttpError::InternalError)?;
+    Ok(buffer)
+}
+
+/// Parses a single-range `Range: bytes=start-end` header into an inclusive `(start, end)`
+/// pair. Supports the open-ended `start-` and suffix `-length` forms. `end` is clamped to
+/// `total - 1`, but `start` is not checked against `end` or `total`; callers must validate
+/// the result against the file length.
+fn parse_range(value: &str, total: u64) -> Option<(u64, u64)> {
+    let spec = value.strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+
+    match (start.is_empty(), end.is_empty()) {
+        (true, true) => None,
+        (true, false) => {
+            let suffix_length: u64 = end.parse().ok()?;
+            let suffix_length = suffix_length.min(total);
+            Some((total.saturating_sub(suffix_length), total.saturating_sub(1)))
+        }
+        (false, true) => Some((start.parse().ok()?, total.saturating_sub(1))),
+        (false, false) => Some((start.parse().ok()?, end.parse::<u64>().ok()?.min(total.saturating_sub(1)))),
+    }
+}
+
 fn write_file(directory: &String, filename: &String, content: &String) -> Result<(), HttpError> {
     let path = format!("{directory}{filename}");
 
@@ -149,3 +327,61 @@ fn assert_method(request: &HttpRequest, accepted: Vec<HttpMethod>) -> Result<(),
         Err(HttpError::MethodNotAllowed(accepted))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_open_ended_range() {
+        assert_eq!(parse_range("bytes=5-", 100), Some((5, 99)));
+    }
+
+    #[test]
+    fn parses_bounded_range() {
+        assert_eq!(parse_range("bytes=5-10", 100), Some((5, 10)));
+    }
+
+    #[test]
+    fn clamps_bounded_range_end_to_last_byte() {
+        assert_eq!(parse_range("bytes=5-1000", 100), Some((5, 99)));
+    }
+
+    #[test]
+    fn clamps_suffix_length_to_total() {
+        assert_eq!(parse_range("bytes=-1000", 100), Some((0, 99)));
+    }
+
+    #[test]
+    fn parses_suffix_range() {
+        assert_eq!(parse_range("bytes=-10", 100), Some((90, 99)));
+    }
+
+    #[test]
+    fn empty_file_range_is_left_for_the_caller_to_reject() {
+        // total - 1 saturates to 0 via saturating_sub, so callers must still reject this
+        // with the `start < total` check at the call site.
+        assert_eq!(parse_range("bytes=0-", 0), Some((0, 0)));
+    }
+
+    #[test]
+    fn reversed_range_is_returned_unvalidated() {
+        // parse_range doesn't reorder or reject start > end; callers filter that out.
+        assert_eq!(parse_range("bytes=10-5", 100), Some((10, 5)));
+    }
+
+    #[test]
+    fn rejects_missing_bytes_prefix() {
+        assert_eq!(parse_range("10-20", 100), None);
+    }
+
+    #[test]
+    fn rejects_multiple_ranges() {
+        assert_eq!(parse_range("bytes=0-10,20-30", 100), None);
+    }
+
+    #[test]
+    fn rejects_empty_spec() {
+        assert_eq!(parse_range("bytes=-", 100), None);
+    }
+}
@@ -0,0 +1,136 @@
+use std::time::Instant;
+
+use crate::http::{HttpError, HttpMethod, HttpRequest, HttpResponse, HttpResponseBuilder};
+
+/// A cross-cutting layer that can inspect or short-circuit a request and post-process the
+/// response produced by the rest of the chain (`next`).
+pub trait Middleware: Send + Sync {
+    fn handle(
+        &self,
+        request: &HttpRequest,
+        next: &dyn Fn(&HttpRequest) -> Result<HttpResponse, HttpError>,
+    ) -> Result<HttpResponse, HttpError>;
+}
+
+/// Folds a stack of middlewares around a router, innermost last, so each layer wraps the
+/// next one in `next`.
+pub struct MiddlewareChain {
+    middlewares: Vec<Box<dyn Middleware>>,
+}
+
+impl MiddlewareChain {
+    pub fn new(middlewares: Vec<Box<dyn Middleware>>) -> Self {
+        Self { middlewares }
+    }
+
+    pub fn run(
+        &self,
+        request: &HttpRequest,
+        router: &dyn Fn(&HttpRequest) -> Result<HttpResponse, HttpError>,
+    ) -> Result<HttpResponse, HttpError> {
+        self.run_from(0, request, router)
+    }
+
+    fn run_from<'a>(
+        &'a self,
+        index: usize,
+        request: &HttpRequest,
+        router: &'a dyn Fn(&HttpRequest) -> Result<HttpResponse, HttpError>,
+    ) -> Result<HttpResponse, HttpError> {
+        match self.middlewares.get(index) {
+            Some(middleware) => {
+                let next = move |req: &HttpRequest| self.run_from(index + 1, req, router);
+                middleware.handle(request, &next)
+            }
+            None => router(request),
+        }
+    }
+}
+
+/// Prints method/path/status/duration for every request that reaches it.
+pub struct RequestLogger;
+
+impl Middleware for RequestLogger {
+    fn handle(
+        &self,
+        request: &HttpRequest,
+        next: &dyn Fn(&HttpRequest) -> Result<HttpResponse, HttpError>,
+    ) -> Result<HttpResponse, HttpError> {
+        let started = Instant::now();
+        let result = next(request);
+        let status = match &result {
+            Ok(response) => response.status_code(),
+            Err(error) => error.to_response().status_code(),
+        };
+        println!(
+            "{} {} {} {:?}",
+            request.method,
+            request.path,
+            status,
+            started.elapsed()
+        );
+        result
+    }
+}
+
+/// Echoes a matching `Origin` back into `Access-Control-Allow-Origin` and answers `OPTIONS`
+/// preflight requests, without ever granting `*` alongside credentials.
+pub struct Cors {
+    allowed_origins: Vec<String>,
+}
+
+impl Cors {
+    pub fn new(allowed_origins: Vec<String>) -> Self {
+        Self { allowed_origins }
+    }
+
+    fn matching_origin(&self, origin: &str) -> Option<&str> {
+        self.allowed_origins
+            .iter()
+            .find(|allowed| allowed.as_str() == origin)
+            .map(String::as_str)
+    }
+}
+
+impl Middleware for Cors {
+    fn handle(
+        &self,
+        request: &HttpRequest,
+        next: &dyn Fn(&HttpRequest) -> Result<HttpResponse, HttpError>,
+    ) -> Result<HttpResponse, HttpError> {
+        let origin = request.headers.get_value(&"Origin".to_string());
+        let matching_origin = origin.as_deref().and_then(|origin| self.matching_origin(origin));
+
+        if request.method == HttpMethod::OPTIONS && origin.is_some() {
+            let mut builder = HttpResponseBuilder::new();
+            if let Some(matching_origin) = matching_origin {
+                builder = builder
+                    .add_header(
+                        "Access-Control-Allow-Origin".to_string(),
+                        matching_origin.to_string(),
+                    )
+                    .add_header(
+                        "Access-Control-Allow-Methods".to_string(),
+                        "GET, POST, PUT, DELETE, OPTIONS".to_string(),
+                    )
+                    .add_header(
+                        "Access-Control-Allow-Headers".to_string(),
+                        "Content-Type".to_string(),
+                    );
+            }
+            return Ok(builder.to_response());
+        }
+
+        let mut response = match next(request) {
+            Ok(response) => response,
+            Err(error) => error.to_response(),
+        };
+        if let Some(matching_origin) = matching_origin {
+            response.add_header(
+                "Access-Control-Allow-Origin".to_string(),
+                matching_origin.to_string(),
+            );
+        }
+        Ok(response)
+    }
+}